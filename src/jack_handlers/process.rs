@@ -1,15 +1,26 @@
+use crate::morse::Edge;
 use anyhow::Result;
 use biquad::{coefficients::Coefficients, Biquad, ToHertz, Type};
 use std::{
     f64::consts::PI,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        mpsc::Sender,
         Arc,
     },
 };
 
+/// Where keying edges are reported for Morse decoding: a channel carrying
+/// the edge durations, plus a waker so the main event loop picks them up
+/// without polling.
+pub struct DecodeSink {
+    pub tx: Sender<Edge>,
+    pub waker: Arc<mio::Waker>,
+}
+
 pub struct Handler {
     port: jack::Port<jack::AudioOut>,
+    midi_port: jack::Port<jack::MidiOut>,
     sidetone_freq: f64,
     sample_rate: Arc<AtomicUsize>,
     last_sample_rate: usize,
@@ -18,9 +29,27 @@ pub struct Handler {
     tx_start_frame_time: u32,
     filter: biquad::DirectForm2Transposed<f64>,
     volume: f32,
+    rise_ms: f64,
+    rise_samples: f64,
+    envelope: f32,
+    envelope_rising: bool,
+    envelope_t: f64,
+    wpm: Arc<AtomicU8>,
+    last_wpm: u8,
+    decode: Option<DecodeSink>,
+    last_edge_frame_time: Option<u32>,
 }
 
 const GAIN: f32 = 0.5;
+const MIDI_KEY_NOTE: u8 = 0x3c;
+const MIDI_WPM_CONTROLLER: u8 = 0x01;
+const MIDI_NOTE_ON: u8 = 0x90;
+const MIDI_NOTE_OFF: u8 = 0x80;
+const MIDI_CONTROL_CHANGE: u8 = 0xb0;
+
+fn rise_samples(sample_rate: usize, rise_ms: f64) -> f64 {
+    rise_ms * sample_rate as f64 / 1000.
+}
 
 fn coefficients(sample_rate: usize, sidetone_freq: f64) -> Coefficients<f64> {
     Coefficients::<f64>::from_params(
@@ -39,12 +68,18 @@ impl Handler {
         sample_rate: Arc<AtomicUsize>,
         tx_key_line: Arc<AtomicBool>,
         volume: f32,
+        rise_ms: f64,
+        wpm: Arc<AtomicU8>,
+        decode: Option<DecodeSink>,
     ) -> Result<Self> {
         let sr = sample_rate.load(Ordering::SeqCst);
+        let rs = rise_samples(sr, rise_ms);
 
         Ok(Handler {
             // register the output port
             port: client.register_port("out", jack::AudioOut)?,
+            // register the MIDI port that mirrors keying and speed
+            midi_port: client.register_port("midi_out", jack::MidiOut)?,
             sidetone_freq,
             sample_rate,
             last_sample_rate: sr,
@@ -53,22 +88,65 @@ impl Handler {
             tx_start_frame_time: 0,
             filter: biquad::DirectForm2Transposed::<f64>::new(coefficients(sr, sidetone_freq)),
             volume,
+            rise_ms,
+            rise_samples: rs,
+            envelope: 0.,
+            envelope_rising: false,
+            // start at rest (settled low) rather than mid-ramp, so the key
+            // being up at launch doesn't play a falling-ramp burst
+            envelope_t: rs,
+            wpm,
+            last_wpm: 0,
+            decode,
+            last_edge_frame_time: None,
         })
     }
 
+    /// Advance the amplitude envelope by one sample and return the gain to
+    /// apply. Ramps follow a half-cosine shape between 0 and 1 over
+    /// `rise_samples`; resuming a reversed ramp picks up from the current
+    /// gain instead of restarting from 0, so a paddle bounce mid-ramp
+    /// doesn't re-click.
+    fn next_envelope(&mut self) -> f32 {
+        if self.rise_samples <= 0. {
+            self.envelope = if self.envelope_rising { 1. } else { 0. };
+            return self.envelope;
+        }
+
+        if self.envelope_t < self.rise_samples {
+            let phase = PI * self.envelope_t / self.rise_samples;
+            self.envelope = if self.envelope_rising {
+                0.5 * (1. - phase.cos()) as f32
+            } else {
+                0.5 * (1. + phase.cos()) as f32
+            };
+            self.envelope_t += 1.;
+        } else {
+            self.envelope = if self.envelope_rising { 1. } else { 0. };
+        }
+
+        self.envelope
+    }
+
     fn write_buf(&mut self, process_scope: &jack::ProcessScope) {
         let step = (2. * PI * self.sidetone_freq) / self.last_sample_rate as f64;
         let buf = self.port.as_mut_slice(process_scope);
         let pos = (process_scope.last_frame_time() - self.tx_start_frame_time) as usize;
 
         for (n, val) in buf.iter_mut().enumerate() {
-            if self.last_tx_key_line {
-                *val = self.filter.run((step * (pos + n) as f64).sin()) as f32;
+            let g = self.next_envelope();
+
+            // Keep driving the oscillator through the key-up release so the
+            // ramp tapers the tone itself, not just the filter's zero-input
+            // decay; only gate to silence once the envelope has settled.
+            let tone = if self.last_tx_key_line || g > 0. {
+                (step * (pos + n) as f64).sin()
             } else {
-                *val = self.filter.run(0.) as f32;
-            }
+                0.
+            };
 
-            *val *= GAIN * self.volume;
+            *val = self.filter.run(tone) as f32;
+            *val *= g * GAIN * self.volume;
         }
     }
 
@@ -81,20 +159,96 @@ impl Handler {
         self.filter
             .update_coefficients(coefficients(sample_rate, self.sidetone_freq));
         self.last_sample_rate = sample_rate;
+        self.rise_samples = rise_samples(sample_rate, self.rise_ms);
     }
 
-    fn update_tx_key_line(&mut self, process_scope: &jack::ProcessScope) {
+    /// Returns `Some(tx_key_line)` when the key line flipped since the last
+    /// call, `None` otherwise.
+    fn update_tx_key_line(&mut self, process_scope: &jack::ProcessScope) -> Option<bool> {
         let tx_key_line = self.tx_key_line.load(Ordering::SeqCst);
 
         if tx_key_line == self.last_tx_key_line {
-            return;
+            return None;
         }
 
         if tx_key_line {
             self.tx_start_frame_time = process_scope.last_frame_time();
         }
 
+        self.report_edge(process_scope);
+
         self.last_tx_key_line = tx_key_line;
+
+        // Re-enter the ramp from wherever the envelope currently sits, rather
+        // than resetting to 0, so a key change mid-ramp doesn't click.
+        self.envelope_rising = tx_key_line;
+        self.envelope_t = if self.rise_samples <= 0. {
+            self.rise_samples
+        } else if self.envelope_rising {
+            (self.rise_samples / PI) * (1. - 2. * self.envelope as f64).acos()
+        } else {
+            (self.rise_samples / PI) * (2. * self.envelope as f64 - 1.).acos()
+        };
+
+        Some(tx_key_line)
+    }
+
+    /// Report the just-finished mark/space segment to the Morse decoder, if
+    /// one is attached. The first edge only establishes the starting frame
+    /// time, since there is no prior segment to measure yet.
+    ///
+    /// This is only invoked on a key transition, not every sample, so it's a
+    /// rare event relative to `write_buf`/`write_midi`; still, `mpsc::Sender`
+    /// and `Waker::wake` aren't wait-free, so this is a (deliberately
+    /// accepted) compromise of the real-time rules described on
+    /// `ProcessHandler::process`.
+    fn report_edge(&mut self, process_scope: &jack::ProcessScope) {
+        let sink = match &self.decode {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let now = process_scope.last_frame_time();
+
+        if let Some(prev) = self.last_edge_frame_time {
+            let _ = sink.tx.send(Edge {
+                key_down: self.last_tx_key_line,
+                frames: now.wrapping_sub(prev),
+                sample_rate: self.last_sample_rate,
+            });
+            let _ = sink.waker.wake();
+        }
+
+        self.last_edge_frame_time = Some(now);
+    }
+
+    /// Mirror keying edges as MIDI note-on/off and the current WPM as a
+    /// control-change, so other JACK/MIDI software can react to keying and
+    /// speed without scraping stdout.
+    fn write_midi(&mut self, process_scope: &jack::ProcessScope, key_edge: Option<bool>) {
+        let mut writer = self.midi_port.writer(process_scope);
+
+        if let Some(key_down) = key_edge {
+            let status = if key_down {
+                MIDI_NOTE_ON
+            } else {
+                MIDI_NOTE_OFF
+            };
+            let velocity = if key_down { 0x7f } else { 0x00 };
+            let _ = writer.write(&jack::RawMidi {
+                time: 0,
+                bytes: &[status, MIDI_KEY_NOTE, velocity],
+            });
+        }
+
+        let wpm = self.wpm.load(Ordering::SeqCst);
+        if wpm != self.last_wpm {
+            let _ = writer.write(&jack::RawMidi {
+                time: 0,
+                bytes: &[MIDI_CONTROL_CHANGE, MIDI_WPM_CONTROLLER, wpm.min(0x7f)],
+            });
+            self.last_wpm = wpm;
+        }
     }
 }
 
@@ -117,8 +271,9 @@ impl jack::ProcessHandler for Handler {
         process_scope: &jack::ProcessScope,
     ) -> jack::Control {
         self.update_sample_rate();
-        self.update_tx_key_line(process_scope);
+        let key_edge = self.update_tx_key_line(process_scope);
         self.write_buf(process_scope);
+        self.write_midi(process_scope, key_edge);
 
         jack::Control::Continue
     }
@@ -1,19 +1,23 @@
 mod jack_handlers;
+mod morse;
 mod winkey;
 
 use anyhow::Result;
 use jack_handlers::{notification, process};
-use mio::{Events, Interest, Poll, Token};
+use mio::{Events, Interest, Poll, Token, Waker};
+use morse::Decoder;
 use signal_hook::consts::TERM_SIGNALS;
 use signal_hook_mio::v0_7::Signals;
 use std::{
-    io::ErrorKind,
+    io::{ErrorKind, IsTerminal, Read},
     sync::{
-        atomic::{AtomicBool, AtomicUsize},
-        Arc,
+        atomic::{AtomicBool, AtomicU8, AtomicUsize},
+        mpsc, Arc,
     },
+    thread,
 };
 use structopt::StructOpt;
+use winkey::KeyerMode;
 
 #[cfg(unix)]
 const DEFAULT_TTY: &str = "/dev/ttyUSB0";
@@ -22,6 +26,36 @@ const DEFAULT_TTY: &str = "COM1";
 
 const SIGNAL: Token = Token(0);
 const SERIAL: Token = Token(1);
+// Both stdin and the Morse decoder wake the main loop through this single
+// token. mio only documents one active `Waker` per `Poll`, so the two
+// background sources share it and the handler below drains whichever
+// channel(s) actually have data instead of assuming which one fired.
+const WAKE: Token = Token(2);
+
+/// Read stdin on a dedicated thread and forward each chunk read to the main
+/// event loop over `tx`, waking `poll` so it doesn't need to be polled.
+fn spawn_stdin_reader(waker: Arc<Waker>) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin = std::io::stdin();
+        let mut buf = [0u8; 1024];
+
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(count) => {
+                    if tx.send(buf[..count].to_vec()).is_err() || waker.wake().is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    rx
+}
 
 #[derive(StructOpt)]
 struct Opt {
@@ -33,6 +67,35 @@ struct Opt {
 
     #[structopt(short = "p", default_value = DEFAULT_TTY)]
     serial_port: String,
+
+    #[structopt(short = "g", long = "volume", default_value = "1.0")]
+    volume: f32,
+
+    #[structopt(long = "rise-ms", default_value = "5.0")]
+    rise_ms: f64,
+
+    #[structopt(
+        long = "keyer-mode",
+        possible_values = &KeyerMode::variants(),
+        case_insensitive = true,
+        default_value = "IambicB"
+    )]
+    keyer_mode: KeyerMode,
+
+    #[structopt(long = "paddle-swap")]
+    paddle_swap: bool,
+
+    #[structopt(long = "autospace")]
+    autospace: bool,
+
+    #[structopt(long = "contest-spacing")]
+    contest_spacing: bool,
+
+    #[structopt(long = "disable-paddle-watchdog")]
+    disable_paddle_watchdog: bool,
+
+    #[structopt(long = "decode")]
+    decode: bool,
 }
 
 fn main() -> Result<()> {
@@ -41,6 +104,15 @@ fn main() -> Result<()> {
     let mut poll = Poll::new()?;
 
     let tx_key_line = Arc::new(AtomicBool::new(false));
+    let wpm = Arc::new(AtomicU8::new(0));
+
+    let mode = winkey::Mode::new(
+        opt.keyer_mode,
+        opt.paddle_swap,
+        opt.autospace,
+        opt.contest_spacing,
+        opt.disable_paddle_watchdog,
+    );
 
     // initialize the keyer
     let mut keyer = winkey::Client::new(
@@ -48,6 +120,8 @@ fn main() -> Result<()> {
         poll.registry(),
         SERIAL,
         Arc::clone(&tx_key_line),
+        Arc::clone(&wpm),
+        mode,
     )?;
 
     // create jack client
@@ -58,11 +132,36 @@ fn main() -> Result<()> {
 
     let sample_rate = Arc::new(AtomicUsize::new(client.sample_rate()));
 
+    // stdin and the Morse decoder (if enabled) both wake the main loop
+    // through this one shared waker
+    let wake_waker = Arc::new(Waker::new(poll.registry(), WAKE)?);
+
+    // optionally decode paddle-sent Morse from tx_key_line edge timing
+    let mut decoder = None;
+    let mut decode_rx = None;
+    let decode_sink = if opt.decode {
+        let (tx, rx) = mpsc::channel();
+
+        decode_rx = Some(rx);
+        decoder = Some(Decoder::new());
+
+        Some(process::DecodeSink {
+            tx,
+            waker: Arc::clone(&wake_waker),
+        })
+    } else {
+        None
+    };
+
     let ph = process::Handler::new(
         &client,
         opt.sidetone_freq,
         Arc::clone(&sample_rate),
         Arc::clone(&tx_key_line),
+        opt.volume,
+        opt.rise_ms,
+        Arc::clone(&wpm),
+        decode_sink,
     )?;
 
     // create the async client
@@ -74,6 +173,12 @@ fn main() -> Result<()> {
     poll.registry()
         .register(&mut signals, SIGNAL, Interest::READABLE)?;
 
+    // forward stdin to the keyer so typed (or piped) text is sent as CW.
+    // `+`/`-` only drive live speed control when stdin is an interactive
+    // terminal, so piped text containing them still transmits faithfully.
+    let stdin_is_tty = std::io::stdin().is_terminal();
+    let stdin_rx = spawn_stdin_reader(Arc::clone(&wake_waker));
+
     // main event loop
     let mut events = Events::with_capacity(16);
     loop {
@@ -90,6 +195,27 @@ fn main() -> Result<()> {
             match event.token() {
                 SIGNAL => return Ok(()),
                 SERIAL => keyer.read()?,
+                WAKE => {
+                    for chunk in stdin_rx.try_iter() {
+                        for &b in &chunk {
+                            match b {
+                                b'+' if stdin_is_tty => keyer.set_wpm(1)?,
+                                b'-' if stdin_is_tty => keyer.set_wpm(-1)?,
+                                // only printable ASCII is valid CW text; forwarding
+                                // anything else (e.g. 0x00, the keyer's admin-command
+                                // prefix) risks it being read as a keyer command
+                                0x20..=0x7e => keyer.send_text(&[b])?,
+                                _ => {}
+                            }
+                        }
+                    }
+
+                    if let (Some(rx), Some(decoder)) = (&decode_rx, &mut decoder) {
+                        for edge in rx.try_iter() {
+                            decoder.on_edge(edge);
+                        }
+                    }
+                }
                 _ => unreachable!(),
             }
         }
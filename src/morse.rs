@@ -0,0 +1,168 @@
+use std::io::{self, Write};
+
+/// One completed key-down ("mark") or key-up ("space") segment, measured in
+/// JACK frames by `process::Handler` from `tx_key_line` edges.
+pub struct Edge {
+    pub key_down: bool,
+    pub frames: u32,
+    pub sample_rate: usize,
+}
+
+/// Decodes paddle-sent Morse from a stream of mark/space edge durations.
+///
+/// The dot length is not configured; it is seeded from the shortest of the
+/// first observed marks and then averaged with every mark classified as a
+/// dot, so the decoder tracks the speed pot without needing the WPM value
+/// itself.
+pub struct Decoder {
+    dot_len_ms: Option<f64>,
+    // buffered until a short and a long mark have both been observed (or
+    // enough marks that they must all be the same symbol), so the very
+    // first element (which may be a dash) can't seed the dot length
+    pending: Vec<(bool, f64)>,
+    element: String,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            dot_len_ms: None,
+            pending: Vec::new(),
+            element: String::new(),
+        }
+    }
+
+    pub fn on_edge(&mut self, edge: Edge) {
+        let duration_ms = f64::from(edge.frames) * 1000. / edge.sample_rate as f64;
+
+        if self.dot_len_ms.is_some() {
+            self.dispatch(edge.key_down, duration_ms);
+        } else {
+            self.learn(edge.key_down, duration_ms);
+        }
+    }
+
+    fn dispatch(&mut self, key_down: bool, duration_ms: f64) {
+        if key_down {
+            self.on_mark(duration_ms);
+        } else {
+            self.on_space(duration_ms);
+        }
+    }
+
+    /// Buffer edges until the dot length can be established from a clearly
+    /// short mark next to a clearly long one (or, failing that, from enough
+    /// marks that they must all be the same symbol).
+    fn learn(&mut self, key_down: bool, duration_ms: f64) {
+        self.pending.push((key_down, duration_ms));
+
+        let marks: Vec<f64> = self
+            .pending
+            .iter()
+            .filter(|(k, _)| *k)
+            .map(|(_, d)| *d)
+            .collect();
+
+        if marks.len() < 2 {
+            return;
+        }
+
+        let min = marks.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = marks.iter().cloned().fold(0., f64::max);
+
+        if max > min * 1.5 || marks.len() >= 8 {
+            self.dot_len_ms = Some(min);
+
+            for (k, d) in std::mem::take(&mut self.pending) {
+                self.dispatch(k, d);
+            }
+        }
+    }
+
+    fn on_mark(&mut self, duration_ms: f64) {
+        let dot_len_ms = self
+            .dot_len_ms
+            .expect("dot length is established before dispatch");
+
+        if duration_ms < dot_len_ms * 2. {
+            // adapt the dot length from observed short elements, so it
+            // tracks the operator's speed pot
+            self.dot_len_ms = Some((dot_len_ms + duration_ms) / 2.);
+            self.element.push('.');
+        } else {
+            self.element.push('-');
+        }
+    }
+
+    fn on_space(&mut self, duration_ms: f64) {
+        let dot_len_ms = self
+            .dot_len_ms
+            .expect("dot length is established before dispatch");
+
+        if duration_ms > dot_len_ms * 5. {
+            self.flush_letter();
+            print!(" ");
+            let _ = io::stdout().flush();
+        } else if duration_ms > dot_len_ms * 2. {
+            self.flush_letter();
+        }
+    }
+
+    fn flush_letter(&mut self) {
+        if self.element.is_empty() {
+            return;
+        }
+
+        print!("{}", lookup(&self.element).unwrap_or('?'));
+        let _ = io::stdout().flush();
+        self.element.clear();
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+fn lookup(code: &str) -> Option<char> {
+    Some(match code {
+        ".-" => 'A',
+        "-..." => 'B',
+        "-.-." => 'C',
+        "-.." => 'D',
+        "." => 'E',
+        "..-." => 'F',
+        "--." => 'G',
+        "...." => 'H',
+        ".." => 'I',
+        ".---" => 'J',
+        "-.-" => 'K',
+        ".-.." => 'L',
+        "--" => 'M',
+        "-." => 'N',
+        "---" => 'O',
+        ".--." => 'P',
+        "--.-" => 'Q',
+        ".-." => 'R',
+        "..." => 'S',
+        "-" => 'T',
+        "..-" => 'U',
+        "...-" => 'V',
+        ".--" => 'W',
+        "-..-" => 'X',
+        "-.--" => 'Y',
+        "--.." => 'Z',
+        "-----" => '0',
+        ".----" => '1',
+        "..---" => '2',
+        "...--" => '3',
+        "....-" => '4',
+        "....." => '5',
+        "-...." => '6',
+        "--..." => '7',
+        "---.." => '8',
+        "----." => '9',
+        _ => return None,
+    })
+}
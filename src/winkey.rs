@@ -8,11 +8,12 @@ use std::{
     io::Write,
     ops,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU8, Ordering},
         Arc,
     },
     time::Duration,
 };
+use structopt::clap::arg_enum;
 
 const LOW_BAUD: u32 = 1200;
 const HIGH_BAUD: u32 = 115200;
@@ -56,19 +57,71 @@ const SPEED_POT_BYTE: u8 = 0x80;
 const SPEED_MASK: u8 = !(1 << 7);
 const TX_KEY_MASK: u8 = 0x1;
 
-struct Mode(u8);
+arg_enum! {
+    /// Keyer paddle mode, as understood by the WinKeyer `SET_WK2_MODE` command.
+    #[derive(Debug, Clone, Copy)]
+    pub enum KeyerMode {
+        IambicA,
+        IambicB,
+        Ultimatic,
+        Bug,
+    }
+}
+
+impl KeyerMode {
+    fn bits(self) -> Mode {
+        match self {
+            KeyerMode::IambicA => Mode::KEY_MODE_IAMBIC_A,
+            KeyerMode::IambicB => Mode::KEY_MODE_IAMBIC_B,
+            KeyerMode::Ultimatic => Mode::KEY_MODE_ULTIMATIC,
+            KeyerMode::Bug => Mode::KEY_MODE_BUG,
+        }
+    }
+}
+
+pub struct Mode(u8);
 
 impl Mode {
-    const _DISABLE_PADDLE_WATCHDOG: Mode = Mode(1 << 7);
+    const DISABLE_PADDLE_WATCHDOG: Mode = Mode(1 << 7);
     const PADDLE_ECHO_BACK: Mode = Mode(1 << 6);
     const KEY_MODE_IAMBIC_B: Mode = Mode(0);
-    const _KEY_MODE_IAMBIC_A: Mode = Mode(1 << 4);
-    const _KEY_MODE_ULTIMATIC: Mode = Mode(1 << 5);
-    const _KEY_MODE_BUG: Mode = Mode((1 << 5) | (1 << 4));
-    const _PADDLE_SWAP: Mode = Mode(1 << 3);
+    const KEY_MODE_IAMBIC_A: Mode = Mode(1 << 4);
+    const KEY_MODE_ULTIMATIC: Mode = Mode(1 << 5);
+    const KEY_MODE_BUG: Mode = Mode((1 << 5) | (1 << 4));
+    const PADDLE_SWAP: Mode = Mode(1 << 3);
     const SERIAL_ECHOBACK: Mode = Mode(1 << 2);
-    const _AUTOSPACE: Mode = Mode(1 << 1);
-    const _CONTEST_SPACING: Mode = Mode(1 << 0);
+    const AUTOSPACE: Mode = Mode(1 << 1);
+    const CONTEST_SPACING: Mode = Mode(1 << 0);
+
+    /// Assemble the `Mode` byte sent to the keyer at startup from the
+    /// operator's chosen paddle behavior. Paddle echo-back and serial
+    /// echo-back are always enabled, since `on_receive` relies on them to
+    /// report what was sent.
+    #[allow(clippy::fn_params_excessive_bools)]
+    pub fn new(
+        keyer_mode: KeyerMode,
+        paddle_swap: bool,
+        autospace: bool,
+        contest_spacing: bool,
+        disable_paddle_watchdog: bool,
+    ) -> Self {
+        let mut mode = Mode::PADDLE_ECHO_BACK | Mode::SERIAL_ECHOBACK | keyer_mode.bits();
+
+        if paddle_swap {
+            mode = mode | Mode::PADDLE_SWAP;
+        }
+        if autospace {
+            mode = mode | Mode::AUTOSPACE;
+        }
+        if contest_spacing {
+            mode = mode | Mode::CONTEST_SPACING;
+        }
+        if disable_paddle_watchdog {
+            mode = mode | Mode::DISABLE_PADDLE_WATCHDOG;
+        }
+
+        mode
+    }
 
     fn option(&self) -> Option<Vec<u8>> {
         return Some([self.0].to_vec());
@@ -89,6 +142,7 @@ pub struct Client {
     buf: Vec<u8>,
     status: u8,
     tx_key_line: Arc<AtomicBool>,
+    wpm: Arc<AtomicU8>,
 }
 
 impl Client {
@@ -97,6 +151,8 @@ impl Client {
         registry: &mio::Registry,
         serial_token: mio::Token,
         tx_key_line: Arc<AtomicBool>,
+        wpm: Arc<AtomicU8>,
+        mode: Mode,
     ) -> Result<Self> {
         let slow_builder = mio_serial::new(path, LOW_BAUD)
             .data_bits(mio_serial::DataBits::Eight)
@@ -116,9 +172,10 @@ impl Client {
             buf: vec![0u8; 1024],
             status: 0,
             tx_key_line,
+            wpm,
         };
 
-        client.initialize()?;
+        client.initialize(mode)?;
 
         Ok(client)
     }
@@ -145,19 +202,45 @@ impl Client {
         Ok(())
     }
 
-    fn initialize(&mut self) -> Result<()> {
-        let mode = Mode::PADDLE_ECHO_BACK | Mode::KEY_MODE_IAMBIC_B | Mode::SERIAL_ECHOBACK;
-
+    fn initialize(&mut self, mode: Mode) -> Result<()> {
         Command::SET_WK2_MODE.send(self, mode.option())?;
 
         println!("WPM: {}", DEFAULT_SPEED);
         Command::SET_WPM_SPEED.send(self, Some(vec![DEFAULT_SPEED]))?;
+        self.wpm.store(DEFAULT_SPEED, Ordering::SeqCst);
 
         Command::SETUP_SPEED_POT.send(self, Some(vec![MIN_SPEED, MAX_SPEED - MIN_SPEED, 0]))?;
 
         Ok(())
     }
 
+    /// Write ASCII text to the keyer's transmit buffer to be sent as CW. The
+    /// echoed characters this produces come back through `on_receive` via
+    /// the serial echo-back mode enabled in `Mode::new`.
+    pub fn send_text(&mut self, data: &[u8]) -> Result<()> {
+        self.write_all(data)?;
+        Ok(())
+    }
+
+    /// Change keying speed live by `delta` WPM, clamped to the speed pot's
+    /// configured range, and push the new speed out over `wpm` for anything
+    /// (e.g. the JACK MIDI port) watching it.
+    pub fn set_wpm(&mut self, delta: i8) -> Result<()> {
+        let current = self.wpm.load(Ordering::SeqCst);
+        let new_speed = (i16::from(current) + i16::from(delta))
+            .clamp(i16::from(MIN_SPEED), i16::from(MAX_SPEED)) as u8;
+
+        if new_speed == current {
+            return Ok(());
+        }
+
+        println!("WPM: {}", new_speed);
+        Command::SET_WPM_SPEED.send(self, Some(vec![new_speed]))?;
+        self.wpm.store(new_speed, Ordering::SeqCst);
+
+        Ok(())
+    }
+
     pub fn read(&mut self) -> Result<()> {
         loop {
             match self.serial.read(&mut self.buf[..]) {
@@ -190,7 +273,9 @@ impl Client {
                 }
             }
             SPEED_POT_BYTE => {
-                println!("\nWPM: {}", (data[0] & SPEED_MASK) + MIN_SPEED);
+                let wpm = (data[0] & SPEED_MASK) + MIN_SPEED;
+                println!("\nWPM: {}", wpm);
+                self.wpm.store(wpm, Ordering::SeqCst);
             }
             _ => {
                 print!("{}", data[0] as char);